@@ -0,0 +1,246 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::proto::validator_public_keys::{
+    ConsensusKeySet as ProtoConsensusKeySet, NodeSignature as ProtoNodeSignature,
+};
+use canonical_serialization::{
+    CanonicalDeserialize, CanonicalDeserializer, CanonicalSerialize, CanonicalSerializer,
+};
+use crypto::bls::{PublicKeySet, PublicKeyShare, Signature as BlsSignature, SignatureShare};
+use failure::{ensure, format_err, Result};
+use proto_conv::{FromProto, IntoProto};
+use std::{collections::BTreeMap, convert::TryFrom};
+
+/// The shared state a group of validators running threshold BLS consensus keys needs: the
+/// master `PublicKeySet`, from which any member's individual `PublicKeyShare` can be derived by
+/// its index. A `NodeSignature` is only meaningful relative to the `ConsensusKeySet` its index
+/// was assigned from.
+#[derive(Clone, Debug)]
+pub struct ConsensusKeySet {
+    public_key_set: PublicKeySet,
+}
+
+impl ConsensusKeySet {
+    pub fn new(public_key_set: PublicKeySet) -> Self {
+        ConsensusKeySet { public_key_set }
+    }
+
+    pub fn public_key_set(&self) -> &PublicKeySet {
+        &self.public_key_set
+    }
+
+    /// Number of valid shares, beyond this threshold, required to recover a group signature.
+    pub fn threshold(&self) -> usize {
+        self.public_key_set.threshold()
+    }
+
+    fn public_key_share(&self, index: u64) -> Result<PublicKeyShare> {
+        let index = usize::try_from(index)
+            .map_err(|_| format_err!("share index {} does not fit in usize", index))?;
+        Ok(self.public_key_set.public_key_share(index))
+    }
+}
+
+impl PartialEq for ConsensusKeySet {
+    fn eq(&self, other: &Self) -> bool {
+        self.public_key_set.public_key() == other.public_key_set.public_key()
+    }
+}
+
+impl Eq for ConsensusKeySet {}
+
+/// One validator's threshold-BLS signature share over a piece of consensus data, tagged with
+/// the signer's index in the owning `ConsensusKeySet`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct NodeSignature {
+    index: u64,
+    share: SignatureShare,
+}
+
+impl NodeSignature {
+    pub fn new(index: u64, share: SignatureShare) -> Self {
+        NodeSignature { index, share }
+    }
+
+    pub fn index(&self) -> u64 {
+        self.index
+    }
+
+    pub fn share(&self) -> &SignatureShare {
+        &self.share
+    }
+
+    /// Checks this share against the public-key share recovered for `index` from `keyset`.
+    pub fn verify_share(&self, payload: &[u8], keyset: &ConsensusKeySet) -> bool {
+        match keyset.public_key_share(self.index) {
+            Ok(public_key_share) => public_key_share.verify(&self.share, payload),
+            Err(_) => false,
+        }
+    }
+}
+
+/// Combines signature shares that verify against `keyset` into a single group signature
+/// verifiable against `keyset`'s master public key. At least `threshold + 1` valid shares are
+/// required, giving consensus a constant-size certificate instead of one signature per
+/// validator.
+pub fn combine(
+    payload: &[u8],
+    shares: &[NodeSignature],
+    keyset: &ConsensusKeySet,
+) -> Result<BlsSignature> {
+    // Dedupe by index: a signer replaying the same share doesn't add a distinct signer, and
+    // feeding the Lagrange interpolation two shares at the same x-coordinate would otherwise
+    // panic instead of just under-counting the number of distinct signers.
+    let valid_shares: BTreeMap<usize, &SignatureShare> = shares
+        .iter()
+        .filter(|node_sig| node_sig.verify_share(payload, keyset))
+        .map(|node_sig| (node_sig.index as usize, &node_sig.share))
+        .collect();
+    ensure!(
+        valid_shares.len() > keyset.threshold(),
+        "need more than {} valid shares to combine a group signature, got {}",
+        keyset.threshold(),
+        valid_shares.len()
+    );
+    keyset
+        .public_key_set
+        .combine_signatures(valid_shares)
+        .map_err(|e| format_err!("failed to combine BLS signature shares: {}", e))
+}
+
+impl FromProto for ConsensusKeySet {
+    type ProtoType = ProtoConsensusKeySet;
+
+    fn from_proto(object: Self::ProtoType) -> Result<Self> {
+        let public_key_set = PublicKeySet::try_from(object.get_public_key_set())
+            .map_err(|e| format_err!("invalid PublicKeySet bytes: {}", e))?;
+        Ok(ConsensusKeySet::new(public_key_set))
+    }
+}
+
+impl IntoProto for ConsensusKeySet {
+    type ProtoType = ProtoConsensusKeySet;
+
+    fn into_proto(self) -> Self::ProtoType {
+        let mut proto = Self::ProtoType::new();
+        proto.set_public_key_set(self.public_key_set.to_bytes());
+        proto
+    }
+}
+
+impl TryFrom<crate::proto::types::ConsensusKeySet> for ConsensusKeySet {
+    type Error = failure::Error;
+
+    fn try_from(proto: crate::proto::types::ConsensusKeySet) -> Result<Self> {
+        let public_key_set = PublicKeySet::try_from(&proto.public_key_set[..])
+            .map_err(|e| format_err!("invalid PublicKeySet bytes: {}", e))?;
+        Ok(ConsensusKeySet::new(public_key_set))
+    }
+}
+
+impl From<ConsensusKeySet> for crate::proto::types::ConsensusKeySet {
+    fn from(keyset: ConsensusKeySet) -> Self {
+        Self {
+            public_key_set: keyset.public_key_set.to_bytes(),
+        }
+    }
+}
+
+impl CanonicalSerialize for ConsensusKeySet {
+    fn serialize(&self, serializer: &mut impl CanonicalSerializer) -> Result<()> {
+        serializer.encode_bytes(&self.public_key_set.to_bytes())?;
+        Ok(())
+    }
+}
+
+impl CanonicalDeserialize for ConsensusKeySet {
+    fn deserialize(deserializer: &mut impl CanonicalDeserializer) -> Result<Self> {
+        let bytes = deserializer.decode_bytes()?;
+        let public_key_set = PublicKeySet::try_from(&bytes[..])
+            .map_err(|e| format_err!("invalid PublicKeySet bytes: {}", e))?;
+        Ok(ConsensusKeySet::new(public_key_set))
+    }
+}
+
+impl FromProto for NodeSignature {
+    type ProtoType = ProtoNodeSignature;
+
+    fn from_proto(object: Self::ProtoType) -> Result<Self> {
+        let index = object.get_index();
+        let share = SignatureShare::try_from(object.get_share())
+            .map_err(|e| format_err!("invalid SignatureShare bytes: {}", e))?;
+        Ok(NodeSignature::new(index, share))
+    }
+}
+
+impl IntoProto for NodeSignature {
+    type ProtoType = ProtoNodeSignature;
+
+    fn into_proto(self) -> Self::ProtoType {
+        let mut proto = Self::ProtoType::new();
+        proto.set_index(self.index);
+        proto.set_share(self.share.to_bytes());
+        proto
+    }
+}
+
+impl TryFrom<crate::proto::types::NodeSignature> for NodeSignature {
+    type Error = failure::Error;
+
+    fn try_from(proto: crate::proto::types::NodeSignature) -> Result<Self> {
+        let share = SignatureShare::try_from(&proto.share[..])
+            .map_err(|e| format_err!("invalid SignatureShare bytes: {}", e))?;
+        Ok(NodeSignature::new(proto.index, share))
+    }
+}
+
+impl From<NodeSignature> for crate::proto::types::NodeSignature {
+    fn from(node_signature: NodeSignature) -> Self {
+        Self {
+            index: node_signature.index,
+            share: node_signature.share.to_bytes(),
+        }
+    }
+}
+
+impl CanonicalSerialize for NodeSignature {
+    fn serialize(&self, serializer: &mut impl CanonicalSerializer) -> Result<()> {
+        serializer
+            .encode_u64(self.index)?
+            .encode_bytes(&self.share.to_bytes())?;
+        Ok(())
+    }
+}
+
+impl CanonicalDeserialize for NodeSignature {
+    fn deserialize(deserializer: &mut impl CanonicalDeserializer) -> Result<Self> {
+        let index = deserializer.decode_u64()?;
+        let share_bytes = deserializer.decode_bytes()?;
+        let share = SignatureShare::try_from(&share_bytes[..])
+            .map_err(|e| format_err!("invalid SignatureShare bytes: {}", e))?;
+        Ok(NodeSignature::new(index, share))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crypto::bls::SecretKeySet;
+
+    // Replaying the same valid share `threshold() + 1` times must not be accepted as that many
+    // distinct signers: it should fail the same way as having too few signers at all, rather
+    // than reaching `combine_signatures` with a duplicated x-coordinate.
+    #[test]
+    fn combine_rejects_duplicate_indices_as_insufficient() {
+        let payload = b"quorum certificate payload";
+        let threshold = 2;
+        let secret_key_set = SecretKeySet::random(threshold);
+        let keyset = ConsensusKeySet::new(secret_key_set.public_keys());
+
+        let share = NodeSignature::new(0, secret_key_set.secret_key_share(0).sign(payload));
+        let duplicated_shares = vec![share.clone(), share.clone(), share.clone()];
+
+        assert!(combine(payload, &duplicated_shares, &keyset).is_err());
+    }
+}
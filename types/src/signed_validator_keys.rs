@@ -0,0 +1,332 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    account_address::AccountAddress,
+    proto::validator_public_keys::SignedValidatorKeys as ProtoSignedValidatorKeys,
+    validator_public_keys::{ConsensusPublicKey, ValidatorPublicKeys},
+};
+use canonical_serialization::{
+    CanonicalDeserialize, CanonicalDeserializer, CanonicalSerialize, CanonicalSerializer,
+    SimpleSerializer,
+};
+use crypto::{
+    ed25519::{Ed25519PrivateKey, Ed25519PublicKey, Ed25519Signature},
+    hash::HashValue,
+};
+use failure::{ensure, Result};
+#[cfg(any(test, feature = "testing"))]
+use proptest_derive::Arbitrary;
+use proto_conv::{FromProto, IntoProto};
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+
+/// A self-authenticated announcement of a validator's (possibly new) keys, broadcast so that
+/// peers can cache and order the latest keys for an account without waiting for the chain to
+/// reflect an epoch change. The announcement is signed by the *current* consensus key, so a
+/// peer that already trusts the old key can authenticate the new one before it rotates in.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(any(test, feature = "testing"), derive(Arbitrary))]
+pub struct SignedValidatorKeys {
+    keys: ValidatorPublicKeys,
+    // Strictly increasing per account_address; a higher version always supersedes a lower one.
+    version: u64,
+    // Wall-clock time in microseconds the announcement was created, for freshness debugging
+    // only -- `version` is the source of truth for ordering.
+    timestamp_us: u64,
+    signature: Ed25519Signature,
+}
+
+impl SignedValidatorKeys {
+    pub fn new(
+        keys: ValidatorPublicKeys,
+        version: u64,
+        timestamp_us: u64,
+        signature: Ed25519Signature,
+    ) -> Self {
+        SignedValidatorKeys {
+            keys,
+            version,
+            timestamp_us,
+            signature,
+        }
+    }
+
+    /// Signs `keys` at `version`/`timestamp_us` with the validator's current consensus private
+    /// key, producing an announcement peers can authenticate with the matching public key.
+    pub fn sign(
+        keys: ValidatorPublicKeys,
+        version: u64,
+        timestamp_us: u64,
+        private_key: &Ed25519PrivateKey,
+    ) -> Result<Self> {
+        let hash = Self::hash_payload(keys.account_address(), version, timestamp_us, &keys)?;
+        let signature = private_key.sign_message(&hash);
+        Ok(Self::new(keys, version, timestamp_us, signature))
+    }
+
+    pub fn keys(&self) -> &ValidatorPublicKeys {
+        &self.keys
+    }
+
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    pub fn timestamp_us(&self) -> u64 {
+        self.timestamp_us
+    }
+
+    pub fn signature(&self) -> &Ed25519Signature {
+        &self.signature
+    }
+
+    /// Authenticates this announcement against the consensus key it carries.
+    pub fn verify(&self) -> Result<()> {
+        let hash = Self::hash_payload(
+            self.keys.account_address(),
+            self.version,
+            self.timestamp_us,
+            &self.keys,
+        )?;
+        let public_key = Self::consensus_ed25519_key(&self.keys)?;
+        public_key
+            .verify_signature(&hash, &self.signature)
+            .map_err(Into::into)
+    }
+
+    /// Merges two announcements for the same account, keeping the one with the higher version.
+    /// Ties break deterministically in favor of the later timestamp, and failing that, the
+    /// lexicographically greater signature so that all peers converge on the same choice.
+    pub fn merge(self, other: Self) -> Self {
+        match self.version.cmp(&other.version) {
+            std::cmp::Ordering::Less => other,
+            std::cmp::Ordering::Greater => self,
+            std::cmp::Ordering::Equal => match self.timestamp_us.cmp(&other.timestamp_us) {
+                std::cmp::Ordering::Less => other,
+                std::cmp::Ordering::Greater => self,
+                std::cmp::Ordering::Equal => {
+                    if self.signature.to_bytes()[..] >= other.signature.to_bytes()[..] {
+                        self
+                    } else {
+                        other
+                    }
+                }
+            },
+        }
+    }
+
+    fn hash_payload(
+        account_address: &AccountAddress,
+        version: u64,
+        timestamp_us: u64,
+        keys: &ValidatorPublicKeys,
+    ) -> Result<HashValue> {
+        let mut serializer = SimpleSerializer::<Vec<u8>>::new();
+        serializer
+            .encode_struct(account_address)?
+            .encode_u64(version)?
+            .encode_u64(timestamp_us)?
+            .encode_struct(keys)?;
+        Ok(HashValue::from_sha3_256(&serializer.get_output()))
+    }
+
+    fn consensus_ed25519_key(keys: &ValidatorPublicKeys) -> Result<&Ed25519PublicKey> {
+        match keys.consensus_public_key() {
+            ConsensusPublicKey::Ed25519(key) => Ok(key),
+            ConsensusPublicKey::Bls(_) => Err(failure::format_err!(
+                "SignedValidatorKeys announcements require an Ed25519 consensus key"
+            )),
+        }
+    }
+}
+
+impl FromProto for SignedValidatorKeys {
+    type ProtoType = ProtoSignedValidatorKeys;
+
+    fn from_proto(mut object: Self::ProtoType) -> Result<Self> {
+        let keys = ValidatorPublicKeys::from_proto(object.take_keys())?;
+        let version = object.get_version();
+        let timestamp_us = object.get_timestamp_us();
+        let signature = Ed25519Signature::try_from(object.get_signature())?;
+        Ok(Self::new(keys, version, timestamp_us, signature))
+    }
+}
+
+impl IntoProto for SignedValidatorKeys {
+    type ProtoType = ProtoSignedValidatorKeys;
+
+    fn into_proto(self) -> Self::ProtoType {
+        let mut proto = Self::ProtoType::new();
+        proto.set_keys(self.keys.into_proto());
+        proto.set_version(self.version);
+        proto.set_timestamp_us(self.timestamp_us);
+        proto.set_signature(self.signature.to_bytes().to_vec());
+        proto
+    }
+}
+
+impl TryFrom<crate::proto::types::SignedValidatorKeys> for SignedValidatorKeys {
+    type Error = failure::Error;
+
+    fn try_from(proto: crate::proto::types::SignedValidatorKeys) -> Result<Self> {
+        ensure!(proto.keys.is_some(), "SignedValidatorKeys is missing keys");
+        let keys = ValidatorPublicKeys::try_from(proto.keys.unwrap())?;
+        let signature = Ed25519Signature::try_from(&proto.signature[..])?;
+        Ok(Self::new(keys, proto.version, proto.timestamp_us, signature))
+    }
+}
+
+impl From<SignedValidatorKeys> for crate::proto::types::SignedValidatorKeys {
+    fn from(signed_keys: SignedValidatorKeys) -> Self {
+        Self {
+            keys: Some(signed_keys.keys.into()),
+            version: signed_keys.version,
+            timestamp_us: signed_keys.timestamp_us,
+            signature: signed_keys.signature.to_bytes().to_vec(),
+        }
+    }
+}
+
+impl CanonicalSerialize for SignedValidatorKeys {
+    fn serialize(&self, serializer: &mut impl CanonicalSerializer) -> Result<()> {
+        serializer
+            .encode_struct(&self.keys)?
+            .encode_u64(self.version)?
+            .encode_u64(self.timestamp_us)?
+            .encode_struct(&self.signature)?;
+        Ok(())
+    }
+}
+
+impl CanonicalDeserialize for SignedValidatorKeys {
+    fn deserialize(deserializer: &mut impl CanonicalDeserializer) -> Result<Self> {
+        let keys: ValidatorPublicKeys = deserializer.decode_struct()?;
+        let version = deserializer.decode_u64()?;
+        let timestamp_us = deserializer.decode_u64()?;
+        let signature: Ed25519Signature = deserializer.decode_struct()?;
+        Ok(SignedValidatorKeys::new(keys, version, timestamp_us, signature))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crypto::{bls::compat::generate_keypair as generate_bls_keypair, ed25519::compat, x25519};
+
+    fn validator_keys(
+        consensus_public_key: ConsensusPublicKey,
+    ) -> (AccountAddress, ValidatorPublicKeys) {
+        let account_address = AccountAddress::random();
+        let (_private_key, network_signing_key) = compat::generate_keypair();
+        let (_private_key, network_identity_key) = x25519::compat::generate_keypair();
+        let keys = ValidatorPublicKeys::new(
+            account_address,
+            consensus_public_key,
+            network_signing_key,
+            network_identity_key,
+        );
+        (account_address, keys)
+    }
+
+    fn ed25519_validator_keys() -> (Ed25519PrivateKey, ValidatorPublicKeys) {
+        let (private_key, consensus_public_key) = compat::generate_keypair();
+        let (_, keys) = validator_keys(ConsensusPublicKey::Ed25519(consensus_public_key));
+        (private_key, keys)
+    }
+
+    #[test]
+    fn sign_then_verify_round_trips() {
+        let (private_key, keys) = ed25519_validator_keys();
+        let signed = SignedValidatorKeys::sign(keys, 1, 1_000, &private_key).unwrap();
+        assert!(signed.verify().is_ok());
+    }
+
+    #[test]
+    fn verify_fails_if_version_is_tampered() {
+        let (private_key, keys) = ed25519_validator_keys();
+        let signed = SignedValidatorKeys::sign(keys, 1, 1_000, &private_key).unwrap();
+        let tampered =
+            SignedValidatorKeys::new(signed.keys, signed.version + 1, signed.timestamp_us, signed.signature);
+        assert!(tampered.verify().is_err());
+    }
+
+    #[test]
+    fn verify_fails_if_timestamp_is_tampered() {
+        let (private_key, keys) = ed25519_validator_keys();
+        let signed = SignedValidatorKeys::sign(keys, 1, 1_000, &private_key).unwrap();
+        let tampered =
+            SignedValidatorKeys::new(signed.keys, signed.version, signed.timestamp_us + 1, signed.signature);
+        assert!(tampered.verify().is_err());
+    }
+
+    #[test]
+    fn verify_fails_if_keys_are_tampered() {
+        let (private_key, keys) = ed25519_validator_keys();
+        let signed = SignedValidatorKeys::sign(keys, 1, 1_000, &private_key).unwrap();
+        let (_, other_keys) = ed25519_validator_keys();
+        let tampered = SignedValidatorKeys::new(
+            other_keys,
+            signed.version,
+            signed.timestamp_us,
+            signed.signature,
+        );
+        assert!(tampered.verify().is_err());
+    }
+
+    #[test]
+    fn verify_errors_instead_of_panicking_for_bls_consensus_key() {
+        let (_private_key, bls_public_key) = generate_bls_keypair();
+        let (_account_address, keys) = validator_keys(ConsensusPublicKey::Bls(bls_public_key));
+        let (signing_private_key, _) = compat::generate_keypair();
+        let signed = SignedValidatorKeys::new(
+            keys,
+            1,
+            1_000,
+            signing_private_key.sign_message(&HashValue::from_sha3_256(b"unused")),
+        );
+        assert!(signed.verify().is_err());
+    }
+
+    #[test]
+    fn merge_breaks_ties_by_version_then_timestamp_then_signature_bytes() {
+        let (private_key, keys) = ed25519_validator_keys();
+
+        // Higher version always wins, regardless of timestamp.
+        let older_version = SignedValidatorKeys::sign(keys.clone(), 1, 2_000, &private_key).unwrap();
+        let newer_version = SignedValidatorKeys::sign(keys.clone(), 2, 1_000, &private_key).unwrap();
+        assert_eq!(
+            older_version.clone().merge(newer_version.clone()),
+            newer_version
+        );
+        assert_eq!(newer_version.clone().merge(older_version), newer_version);
+
+        // Same version: later timestamp wins.
+        let earlier = SignedValidatorKeys::sign(keys.clone(), 1, 1_000, &private_key).unwrap();
+        let later = SignedValidatorKeys::sign(keys.clone(), 1, 2_000, &private_key).unwrap();
+        assert_eq!(earlier.clone().merge(later.clone()), later);
+        assert_eq!(later.clone().merge(earlier), later);
+
+        // Same version and timestamp: lexicographically greater signature wins, deterministically
+        // regardless of argument order.
+        let one = SignedValidatorKeys::new(
+            keys.clone(),
+            1,
+            1_000,
+            Ed25519PrivateKey::try_from(&[1u8; 32][..]).unwrap().sign_message(&HashValue::zero()),
+        );
+        let other = SignedValidatorKeys::new(
+            keys,
+            1,
+            1_000,
+            Ed25519PrivateKey::try_from(&[2u8; 32][..]).unwrap().sign_message(&HashValue::zero()),
+        );
+        let winner = if one.signature.to_bytes()[..] >= other.signature.to_bytes()[..] {
+            one.clone()
+        } else {
+            other.clone()
+        };
+        assert_eq!(one.clone().merge(other.clone()), winner);
+        assert_eq!(other.merge(one), winner);
+    }
+}
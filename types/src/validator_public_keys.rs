@@ -8,14 +8,106 @@ use crate::{
 use canonical_serialization::{
     CanonicalDeserialize, CanonicalDeserializer, CanonicalSerialize, CanonicalSerializer,
 };
-use crypto::{ed25519::*, traits::ValidKey, x25519::X25519StaticPublicKey};
-use failure::Result;
+use crypto::{bls::BlsPublicKey, ed25519::*, traits::ValidKey, x25519::X25519StaticPublicKey};
+use failure::{ensure, format_err, Result};
+use once_cell::sync::OnceCell;
 #[cfg(any(test, feature = "testing"))]
 use proptest_derive::Arbitrary;
 use proto_conv::{FromProto, IntoProto};
 use serde::{Deserialize, Serialize};
 use std::{convert::TryFrom, fmt};
 
+/// Identifies which signature scheme a [`ConsensusPublicKey`] was built with. This is the
+/// one-byte discriminant that gets prefixed onto every wire and canonical encoding of the key
+/// so that old single-scheme (Ed25519-only) encodings and newer tagged encodings never collide.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(u8)]
+pub enum ConsensusScheme {
+    Ed25519 = 0,
+    Bls = 1,
+}
+
+/// The public key a validator uses to authenticate its consensus votes. Consensus is no longer
+/// tied to a single signature scheme: a validator can run Ed25519, where quorum certificates are
+/// formed by collecting N individual signatures, or BLS, where the per-validator signatures
+/// aggregate into a single constant-size signature.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(any(test, feature = "testing"), derive(Arbitrary))]
+pub enum ConsensusPublicKey {
+    Ed25519(Ed25519PublicKey),
+    Bls(BlsPublicKey),
+}
+
+impl ConsensusPublicKey {
+    /// Returns the signature scheme tag for this key.
+    pub fn scheme(&self) -> ConsensusScheme {
+        match self {
+            ConsensusPublicKey::Ed25519(_) => ConsensusScheme::Ed25519,
+            ConsensusPublicKey::Bls(_) => ConsensusScheme::Bls,
+        }
+    }
+
+    /// Serializes this key as a one-byte scheme discriminant followed by the scheme's native
+    /// byte encoding. This is the flat proto wire format: like every sibling field in
+    /// `ValidatorPublicKeys`, it is independent of whatever framing `CanonicalSerialize` below
+    /// adds for the canonical (on-chain) encoding.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![self.scheme() as u8];
+        match self {
+            ConsensusPublicKey::Ed25519(key) => bytes.extend_from_slice(&key.to_bytes()),
+            ConsensusPublicKey::Bls(key) => bytes.extend_from_slice(&key.to_bytes()),
+        }
+        bytes
+    }
+}
+
+impl TryFrom<&[u8]> for ConsensusPublicKey {
+    type Error = failure::Error;
+
+    fn try_from(bytes: &[u8]) -> Result<Self> {
+        let (scheme, key_bytes) = bytes
+            .split_first()
+            .ok_or_else(|| format_err!("ConsensusPublicKey bytes are empty"))?;
+        match *scheme {
+            scheme if scheme == ConsensusScheme::Ed25519 as u8 => {
+                Ok(ConsensusPublicKey::Ed25519(Ed25519PublicKey::try_from(
+                    key_bytes,
+                )?))
+            }
+            scheme if scheme == ConsensusScheme::Bls as u8 => Ok(ConsensusPublicKey::Bls(
+                BlsPublicKey::try_from(key_bytes)?,
+            )),
+            scheme => Err(format_err!("unknown ConsensusPublicKey scheme: {}", scheme)),
+        }
+    }
+}
+
+impl CanonicalSerialize for ConsensusPublicKey {
+    fn serialize(&self, serializer: &mut impl CanonicalSerializer) -> Result<()> {
+        serializer.encode_u8(self.scheme() as u8)?;
+        match self {
+            ConsensusPublicKey::Ed25519(key) => serializer.encode_struct(key)?,
+            ConsensusPublicKey::Bls(key) => serializer.encode_struct(key)?,
+        };
+        Ok(())
+    }
+}
+
+impl CanonicalDeserialize for ConsensusPublicKey {
+    fn deserialize(deserializer: &mut impl CanonicalDeserializer) -> Result<Self> {
+        let scheme = deserializer.decode_u8()?;
+        match scheme {
+            scheme if scheme == ConsensusScheme::Ed25519 as u8 => {
+                Ok(ConsensusPublicKey::Ed25519(deserializer.decode_struct()?))
+            }
+            scheme if scheme == ConsensusScheme::Bls as u8 => {
+                Ok(ConsensusPublicKey::Bls(deserializer.decode_struct()?))
+            }
+            scheme => Err(format_err!("unknown ConsensusPublicKey scheme: {}", scheme)),
+        }
+    }
+}
+
 /// After executing a special transaction that sets the validators that should be used for the
 /// next epoch, consensus and networking get the new list of validators.  Consensus will have a
 /// public key to validate signed messages and networking will have a TBD public key for
@@ -27,12 +119,16 @@ pub struct ValidatorPublicKeys {
     // Hash value of the current public key of the account address
     account_address: AccountAddress,
     // This key can validate messages sent from this validator
-    consensus_public_key: Ed25519PublicKey,
+    consensus_public_key: ConsensusPublicKey,
     // This key can validate signed messages at the network layer
     network_signing_public_key: Ed25519PublicKey,
     // This key establishes the corresponding PrivateKey holder's eligibility to join the p2p
     // network
     network_identity_public_key: X25519StaticPublicKey,
+    // This validator's index into the `PublicKeySet` of a threshold-BLS `ConsensusKeySet`, if
+    // it participates in one. `None` for validators that only sign with their individual
+    // `consensus_public_key`.
+    consensus_key_share_index: Option<u64>,
 }
 
 impl fmt::Display for ValidatorPublicKeys {
@@ -44,7 +140,7 @@ impl fmt::Display for ValidatorPublicKeys {
 impl ValidatorPublicKeys {
     pub fn new(
         account_address: AccountAddress,
-        consensus_public_key: Ed25519PublicKey,
+        consensus_public_key: ConsensusPublicKey,
         network_signing_public_key: Ed25519PublicKey,
         network_identity_public_key: X25519StaticPublicKey,
     ) -> Self {
@@ -53,17 +149,31 @@ impl ValidatorPublicKeys {
             consensus_public_key,
             network_signing_public_key,
             network_identity_public_key,
+            consensus_key_share_index: None,
         }
     }
 
+    /// Records this validator's index into a threshold-BLS `ConsensusKeySet`, so its
+    /// `NodeSignature`s can be matched against the right `PublicKeyShare`.
+    pub fn with_consensus_key_share_index(mut self, index: u64) -> Self {
+        self.consensus_key_share_index = Some(index);
+        self
+    }
+
     /// Returns the id of this validator (hash of the current public key of the
     /// validator associated account address)
     pub fn account_address(&self) -> &AccountAddress {
         &self.account_address
     }
 
+    /// Returns this validator's share index within its `ConsensusKeySet`, if it participates in
+    /// threshold-BLS consensus.
+    pub fn consensus_key_share_index(&self) -> Option<u64> {
+        self.consensus_key_share_index
+    }
+
     /// Returns the key for validating signed messages from this validator
-    pub fn consensus_public_key(&self) -> &Ed25519PublicKey {
+    pub fn consensus_public_key(&self) -> &ConsensusPublicKey {
         &self.consensus_public_key
     }
 
@@ -83,17 +193,22 @@ impl FromProto for ValidatorPublicKeys {
 
     fn from_proto(object: Self::ProtoType) -> Result<Self> {
         let account_address = AccountAddress::from_proto(object.get_account_address().to_vec())?;
-        let consensus_public_key = Ed25519PublicKey::try_from(object.get_consensus_public_key())?;
+        let consensus_public_key =
+            ConsensusPublicKey::try_from(object.get_consensus_public_key())?;
         let network_signing_public_key =
             Ed25519PublicKey::try_from(object.get_network_signing_public_key())?;
         let network_identity_public_key =
             X25519StaticPublicKey::try_from(object.get_network_identity_public_key())?;
-        Ok(Self::new(
+        let mut keys = Self::new(
             account_address,
             consensus_public_key,
             network_signing_public_key,
             network_identity_public_key,
-        ))
+        );
+        if object.has_consensus_key_share_index() {
+            keys = keys.with_consensus_key_share_index(object.get_consensus_key_share_index());
+        }
+        Ok(keys)
     }
 }
 
@@ -103,15 +218,16 @@ impl IntoProto for ValidatorPublicKeys {
     fn into_proto(self) -> Self::ProtoType {
         let mut proto = Self::ProtoType::new();
         proto.set_account_address(AccountAddress::into_proto(self.account_address));
-        proto.set_consensus_public_key(
-            Ed25519PublicKey::to_bytes(&self.consensus_public_key).to_vec(),
-        );
+        proto.set_consensus_public_key(self.consensus_public_key.to_bytes());
         proto.set_network_signing_public_key(
             Ed25519PublicKey::to_bytes(&self.network_signing_public_key).to_vec(),
         );
         proto.set_network_identity_public_key(
             X25519StaticPublicKey::to_bytes(&self.network_identity_public_key).to_vec(),
         );
+        if let Some(index) = self.consensus_key_share_index {
+            proto.set_consensus_key_share_index(index);
+        }
         proto
     }
 }
@@ -121,17 +237,21 @@ impl TryFrom<crate::proto::types::ValidatorPublicKeys> for ValidatorPublicKeys {
 
     fn try_from(proto: crate::proto::types::ValidatorPublicKeys) -> Result<Self> {
         let account_address = AccountAddress::try_from(proto.account_address)?;
-        let consensus_public_key = Ed25519PublicKey::try_from(&proto.consensus_public_key[..])?;
+        let consensus_public_key = ConsensusPublicKey::try_from(&proto.consensus_public_key[..])?;
         let network_signing_public_key =
             Ed25519PublicKey::try_from(&proto.network_signing_public_key[..])?;
         let network_identity_public_key =
             X25519StaticPublicKey::try_from(&proto.network_identity_public_key[..])?;
-        Ok(Self::new(
+        let mut keys = Self::new(
             account_address,
             consensus_public_key,
             network_signing_public_key,
             network_identity_public_key,
-        ))
+        );
+        if let Some(index) = proto.consensus_key_share_index {
+            keys = keys.with_consensus_key_share_index(index);
+        }
+        Ok(keys)
     }
 }
 
@@ -139,35 +259,181 @@ impl From<ValidatorPublicKeys> for crate::proto::types::ValidatorPublicKeys {
     fn from(keys: ValidatorPublicKeys) -> Self {
         Self {
             account_address: keys.account_address.to_vec(),
-            consensus_public_key: keys.consensus_public_key.to_bytes().to_vec(),
+            consensus_public_key: keys.consensus_public_key.to_bytes(),
             network_signing_public_key: keys.network_signing_public_key.to_bytes().to_vec(),
             network_identity_public_key: keys.network_identity_public_key.to_bytes().to_vec(),
+            consensus_key_share_index: keys.consensus_key_share_index,
         }
     }
 }
 
 impl CanonicalSerialize for ValidatorPublicKeys {
+    fn serialize(&self, serializer: &mut impl CanonicalSerializer) -> Result<()> {
+        serializer.encode_struct(&ValidatorKeyBytes::from(self.clone()))?;
+        Ok(())
+    }
+}
+
+impl CanonicalDeserialize for ValidatorPublicKeys {
+    fn deserialize(deserializer: &mut impl CanonicalDeserializer) -> Result<Self> {
+        let key_bytes: ValidatorKeyBytes = deserializer.decode_struct()?;
+        key_bytes.decode().map(Clone::clone)
+    }
+}
+
+/// A byte-level refinement of [`ValidatorPublicKeys`] that defers the expensive Ed25519/X25519
+/// point decompression until the keys are actually needed. Construction only checks that each
+/// field is present; the first call to [`decode`](Self::decode) parses the curve points and
+/// caches the result behind a `OnceCell`, so a node that ingests a large validator set but only
+/// verifies signatures from a handful of members never pays to decompress the rest.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ValidatorKeyBytes {
+    account_address: AccountAddress,
+    consensus_public_key: Vec<u8>,
+    network_signing_public_key: [u8; 32],
+    network_identity_public_key: [u8; 32],
+    consensus_key_share_index: Option<u64>,
+    #[serde(skip)]
+    decoded: OnceCell<ValidatorPublicKeys>,
+}
+
+impl ValidatorKeyBytes {
+    pub fn new(
+        account_address: AccountAddress,
+        consensus_public_key: Vec<u8>,
+        network_signing_public_key: [u8; 32],
+        network_identity_public_key: [u8; 32],
+        consensus_key_share_index: Option<u64>,
+    ) -> Result<Self> {
+        ensure!(
+            !consensus_public_key.is_empty(),
+            "consensus_public_key bytes must not be empty"
+        );
+        Ok(ValidatorKeyBytes {
+            account_address,
+            consensus_public_key,
+            network_signing_public_key,
+            network_identity_public_key,
+            consensus_key_share_index,
+            decoded: OnceCell::new(),
+        })
+    }
+
+    pub fn account_address(&self) -> &AccountAddress {
+        &self.account_address
+    }
+
+    pub fn consensus_public_key_bytes(&self) -> &[u8] {
+        &self.consensus_public_key
+    }
+
+    pub fn network_signing_public_key_bytes(&self) -> &[u8; 32] {
+        &self.network_signing_public_key
+    }
+
+    pub fn network_identity_public_key_bytes(&self) -> &[u8; 32] {
+        &self.network_identity_public_key
+    }
+
+    pub fn consensus_key_share_index(&self) -> Option<u64> {
+        self.consensus_key_share_index
+    }
+
+    /// Parses and validates the curve points, caching the result so repeated calls are free.
+    pub fn decode(&self) -> Result<&ValidatorPublicKeys> {
+        self.decoded.get_or_try_init(|| {
+            let consensus_public_key =
+                ConsensusPublicKey::try_from(&self.consensus_public_key[..])?;
+            let network_signing_public_key =
+                Ed25519PublicKey::try_from(&self.network_signing_public_key[..])?;
+            let network_identity_public_key =
+                X25519StaticPublicKey::try_from(&self.network_identity_public_key[..])?;
+            let mut keys = ValidatorPublicKeys::new(
+                self.account_address.clone(),
+                consensus_public_key,
+                network_signing_public_key,
+                network_identity_public_key,
+            );
+            if let Some(index) = self.consensus_key_share_index {
+                keys = keys.with_consensus_key_share_index(index);
+            }
+            Ok(keys)
+        })
+    }
+}
+
+impl PartialEq for ValidatorKeyBytes {
+    fn eq(&self, other: &Self) -> bool {
+        self.account_address == other.account_address
+            && self.consensus_public_key == other.consensus_public_key
+            && self.network_signing_public_key == other.network_signing_public_key
+            && self.network_identity_public_key == other.network_identity_public_key
+            && self.consensus_key_share_index == other.consensus_key_share_index
+    }
+}
+
+impl Eq for ValidatorKeyBytes {}
+
+impl From<ValidatorPublicKeys> for ValidatorKeyBytes {
+    fn from(keys: ValidatorPublicKeys) -> Self {
+        let account_address = keys.account_address.clone();
+        let consensus_public_key = keys.consensus_public_key.to_bytes();
+        let network_signing_public_key = keys.network_signing_public_key.to_bytes();
+        let network_identity_public_key = keys.network_identity_public_key.to_bytes();
+        let consensus_key_share_index = keys.consensus_key_share_index;
+        let key_bytes = ValidatorKeyBytes {
+            account_address,
+            consensus_public_key,
+            network_signing_public_key,
+            network_identity_public_key,
+            consensus_key_share_index,
+            decoded: OnceCell::new(),
+        };
+        // We already have a fully-decoded `ValidatorPublicKeys`; seed the cache so a later
+        // `decode()` call doesn't needlessly re-parse what we were just given.
+        let _ = key_bytes.decoded.set(keys);
+        key_bytes
+    }
+}
+
+impl CanonicalSerialize for ValidatorKeyBytes {
     fn serialize(&self, serializer: &mut impl CanonicalSerializer) -> Result<()> {
         serializer
             .encode_struct(&self.account_address)?
-            .encode_struct(&self.consensus_public_key)?
-            .encode_struct(&self.network_signing_public_key)?
-            .encode_struct(&self.network_identity_public_key)?;
+            .encode_bytes(&self.consensus_public_key)?
+            .encode_bytes(&self.network_signing_public_key)?
+            .encode_bytes(&self.network_identity_public_key)?;
+        match self.consensus_key_share_index {
+            Some(index) => serializer.encode_bool(true)?.encode_u64(index)?,
+            None => serializer.encode_bool(false)?,
+        };
         Ok(())
     }
 }
 
-impl CanonicalDeserialize for ValidatorPublicKeys {
+impl CanonicalDeserialize for ValidatorKeyBytes {
     fn deserialize(deserializer: &mut impl CanonicalDeserializer) -> Result<Self> {
         let account_address: AccountAddress = deserializer.decode_struct()?;
-        let consensus_public_key: Ed25519PublicKey = deserializer.decode_struct()?;
-        let network_signing_public_key: Ed25519PublicKey = deserializer.decode_struct()?;
-        let network_identity_public_key: X25519StaticPublicKey = deserializer.decode_struct()?;
-        Ok(ValidatorPublicKeys::new(
+        let consensus_public_key = deserializer.decode_bytes()?;
+        let network_signing_public_key_bytes = deserializer.decode_bytes()?;
+        let network_identity_public_key_bytes = deserializer.decode_bytes()?;
+        let network_signing_public_key =
+            <[u8; 32]>::try_from(&network_signing_public_key_bytes[..])
+                .map_err(|_| format_err!("network_signing_public_key must be 32 bytes"))?;
+        let network_identity_public_key =
+            <[u8; 32]>::try_from(&network_identity_public_key_bytes[..])
+                .map_err(|_| format_err!("network_identity_public_key must be 32 bytes"))?;
+        let consensus_key_share_index = if deserializer.decode_bool()? {
+            Some(deserializer.decode_u64()?)
+        } else {
+            None
+        };
+        ValidatorKeyBytes::new(
             account_address,
             consensus_public_key,
             network_signing_public_key,
             network_identity_public_key,
-        ))
+            consensus_key_share_index,
+        )
     }
 }
@@ -0,0 +1,233 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    account_address::AccountAddress,
+    proto::validator_set::ValidatorSet as ProtoValidatorSet,
+    validator_public_keys::ValidatorPublicKeys,
+};
+use canonical_serialization::{
+    CanonicalDeserialize, CanonicalDeserializer, CanonicalSerialize, CanonicalSerializer,
+};
+use failure::Result;
+#[cfg(any(test, feature = "testing"))]
+use proptest_derive::Arbitrary;
+use proto_conv::{FromProto, IntoProto};
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+
+/// A single validator's membership in a [`ValidatorSet`]: its public keys together with the
+/// voting power it is entitled to cast.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(any(test, feature = "testing"), derive(Arbitrary))]
+pub struct ValidatorSetEntry {
+    voting_power: u64,
+    keys: ValidatorPublicKeys,
+}
+
+impl ValidatorSetEntry {
+    pub fn new(voting_power: u64, keys: ValidatorPublicKeys) -> Self {
+        ValidatorSetEntry { voting_power, keys }
+    }
+
+    pub fn voting_power(&self) -> u64 {
+        self.voting_power
+    }
+
+    pub fn keys(&self) -> &ValidatorPublicKeys {
+        &self.keys
+    }
+}
+
+/// The ordered set of validators for an epoch, together with the voting power of each member.
+/// This lets consensus weigh collected signatures by power rather than by headcount when
+/// deciding whether a quorum certificate has been formed.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(any(test, feature = "testing"), derive(Arbitrary))]
+pub struct ValidatorSet(Vec<ValidatorSetEntry>);
+
+impl ValidatorSet {
+    pub fn new(entries: Vec<ValidatorSetEntry>) -> Self {
+        ValidatorSet(entries)
+    }
+
+    pub fn payload(&self) -> &[ValidatorSetEntry] {
+        &self.0
+    }
+
+    /// Returns the sum of the voting power of every validator in the set, accumulated in `u128`
+    /// so a set with enough members (or crafted wire data) can't silently wrap a `u64` sum.
+    pub fn total_power(&self) -> u128 {
+        self.0
+            .iter()
+            .map(|entry| u128::from(entry.voting_power))
+            .sum()
+    }
+
+    /// Returns the minimum voting power a set of signatures must carry to form a quorum
+    /// certificate: `floor(2/3 * total_power) + 1`, computed entirely in `u128` so the
+    /// multiplication cannot overflow even when `total_power` is close to `u64::MAX`.
+    pub fn quorum_voting_power(&self) -> u128 {
+        (self.total_power() * 2 / 3) + 1
+    }
+
+    /// Looks up a validator by account address, returning its public keys and voting power.
+    pub fn find(&self, account_address: &AccountAddress) -> Option<(&ValidatorPublicKeys, u64)> {
+        self.0
+            .iter()
+            .find(|entry| entry.keys.account_address() == account_address)
+            .map(|entry| (&entry.keys, entry.voting_power))
+    }
+}
+
+impl FromProto for ValidatorSetEntry {
+    type ProtoType = crate::proto::validator_set::ValidatorSetEntry;
+
+    fn from_proto(mut object: Self::ProtoType) -> Result<Self> {
+        let voting_power = object.get_voting_power();
+        let keys = ValidatorPublicKeys::from_proto(object.take_keys())?;
+        Ok(ValidatorSetEntry::new(voting_power, keys))
+    }
+}
+
+impl IntoProto for ValidatorSetEntry {
+    type ProtoType = crate::proto::validator_set::ValidatorSetEntry;
+
+    fn into_proto(self) -> Self::ProtoType {
+        let mut proto = Self::ProtoType::new();
+        proto.set_voting_power(self.voting_power);
+        proto.set_keys(self.keys.into_proto());
+        proto
+    }
+}
+
+impl FromProto for ValidatorSet {
+    type ProtoType = ProtoValidatorSet;
+
+    fn from_proto(mut object: Self::ProtoType) -> Result<Self> {
+        let entries = object
+            .take_validator_entries()
+            .into_iter()
+            .map(ValidatorSetEntry::from_proto)
+            .collect::<Result<Vec<_>>>()?;
+        Ok(ValidatorSet::new(entries))
+    }
+}
+
+impl IntoProto for ValidatorSet {
+    type ProtoType = ProtoValidatorSet;
+
+    fn into_proto(self) -> Self::ProtoType {
+        let mut proto = Self::ProtoType::new();
+        proto.set_validator_entries(
+            self.0
+                .into_iter()
+                .map(ValidatorSetEntry::into_proto)
+                .collect(),
+        );
+        proto
+    }
+}
+
+impl TryFrom<crate::proto::types::ValidatorSet> for ValidatorSet {
+    type Error = failure::Error;
+
+    fn try_from(proto: crate::proto::types::ValidatorSet) -> Result<Self> {
+        let entries = proto
+            .validator_entries
+            .into_iter()
+            .map(|entry| -> Result<ValidatorSetEntry> {
+                let keys = ValidatorPublicKeys::try_from(
+                    entry.keys.ok_or_else(|| {
+                        failure::format_err!("ValidatorSetEntry is missing keys")
+                    })?,
+                )?;
+                Ok(ValidatorSetEntry::new(entry.voting_power, keys))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(ValidatorSet::new(entries))
+    }
+}
+
+impl From<ValidatorSet> for crate::proto::types::ValidatorSet {
+    fn from(validator_set: ValidatorSet) -> Self {
+        Self {
+            validator_entries: validator_set
+                .0
+                .into_iter()
+                .map(|entry| crate::proto::types::ValidatorSetEntry {
+                    voting_power: entry.voting_power,
+                    keys: Some(entry.keys.into()),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl CanonicalSerialize for ValidatorSetEntry {
+    fn serialize(&self, serializer: &mut impl CanonicalSerializer) -> Result<()> {
+        serializer
+            .encode_u64(self.voting_power)?
+            .encode_struct(&self.keys)?;
+        Ok(())
+    }
+}
+
+impl CanonicalDeserialize for ValidatorSetEntry {
+    fn deserialize(deserializer: &mut impl CanonicalDeserializer) -> Result<Self> {
+        let voting_power = deserializer.decode_u64()?;
+        let keys: ValidatorPublicKeys = deserializer.decode_struct()?;
+        Ok(ValidatorSetEntry::new(voting_power, keys))
+    }
+}
+
+impl CanonicalSerialize for ValidatorSet {
+    fn serialize(&self, serializer: &mut impl CanonicalSerializer) -> Result<()> {
+        serializer.encode_struct(&self.0)?;
+        Ok(())
+    }
+}
+
+impl CanonicalDeserialize for ValidatorSet {
+    fn deserialize(deserializer: &mut impl CanonicalDeserializer) -> Result<Self> {
+        let entries: Vec<ValidatorSetEntry> = deserializer.decode_struct()?;
+        Ok(ValidatorSet::new(entries))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validator_public_keys::ConsensusPublicKey;
+    use crypto::{ed25519::compat::generate_keypair, traits::ValidKey, x25519};
+
+    fn dummy_keys() -> ValidatorPublicKeys {
+        let (_private_key, consensus_key) = generate_keypair();
+        let (_private_key, network_signing_key) = generate_keypair();
+        let (_private_key, network_identity_key) = x25519::compat::generate_keypair();
+        ValidatorPublicKeys::new(
+            AccountAddress::random(),
+            ConsensusPublicKey::Ed25519(consensus_key),
+            network_signing_key,
+            network_identity_key,
+        )
+    }
+
+    // A set of entries whose `voting_power` values individually fit in a `u64` but whose sum
+    // overflows `u64::MAX` must not wrap around to a small quorum threshold.
+    #[test]
+    fn total_power_does_not_overflow_u64() {
+        let entries = vec![
+            ValidatorSetEntry::new(u64::max_value(), dummy_keys()),
+            ValidatorSetEntry::new(u64::max_value(), dummy_keys()),
+        ];
+        let validator_set = ValidatorSet::new(entries);
+
+        let expected_total = 2 * u128::from(u64::max_value());
+        assert_eq!(validator_set.total_power(), expected_total);
+        assert_eq!(
+            validator_set.quorum_voting_power(),
+            (expected_total * 2 / 3) + 1
+        );
+    }
+}